@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 /// Inheritors of this trait are capable of testing by accessibility interfaces.
 pub trait TestByA11y: Sized {
     /// The data type needed for initialisation of a connection to this interface.
@@ -13,11 +15,34 @@ pub trait TestByA11y: Sized {
     /// Find an node by the specified query.
     fn find(&mut self, by: By) -> Result<Option<Self::Node>, Self::Error>;
 
+    /// Find all nodes matching the specified query.
+    ///
+    /// Mostly useful with [`By::And`], [`By::Or`] and [`By::Not`], which frequently match
+    /// more than one node.
+    fn find_all(&mut self, by: By) -> Result<Vec<Self::Node>, Self::Error>;
+
     /// Interact with a node.
     fn interact(&mut self, node: &Self::Node, interaction: Interaction) -> Result<(), Self::Error>;
 
     /// Get the text from a node.
     fn get_text(&mut self, node: &Self::Node) -> Result<String, Self::Error>;
+
+    /// Get the caret offset within a node's text, in characters.
+    ///
+    /// Returns `0` for nodes that do not expose a text interface.
+    fn get_caret_offset(&mut self, node: &Self::Node) -> Result<usize, Self::Error>;
+
+    /// Get the current text selection within a node, as a `(start, end)` character range.
+    ///
+    /// Returns `None` if the node has no text interface, or if nothing is currently selected.
+    fn get_selection(&mut self, node: &Self::Node) -> Result<Option<(usize, usize)>, Self::Error>;
+
+    /// Wait until a node matching `by` appears, or `timeout` elapses.
+    ///
+    /// Rather than polling, implementations should drive this from the accessibility
+    /// interface's own change events, re-checking only when something relevant happens.
+    /// Returns `Ok(None)` if no matching node appears before the timeout.
+    fn wait_for(&mut self, by: By, timeout: Duration) -> Result<Option<Self::Node>, Self::Error>;
 }
 
 /// Ways we can find nodes
@@ -25,13 +50,41 @@ pub trait TestByA11y: Sized {
 pub enum By {
     /// By a tag that is machine-visible. On Linux, this is the accessibility ID.
     Tag(String),
-    /// By some human readable text. This should match partially as well.
+    /// By some human readable text. This matches partially, as a case-sensitive substring
+    /// of the node's name.
     Text(String),
+    /// By a substring of a node's name, optionally ignoring case.
+    TextContains {
+        /// The substring to search for.
+        needle: String,
+        /// Whether to ignore case when comparing.
+        case_insensitive: bool,
+    },
+    /// By a [`regex::Regex`] pattern matched against a node's name.
+    TextRegex(String),
+    /// By the accessible role of a node, e.g. a button or a checkbox.
+    Role(atspi::Role),
+    /// By a state that a node currently holds, e.g. focused, checked, or enabled.
+    State(atspi::State),
+    /// Matches a node only if both of the given queries match it.
+    And(Box<By>, Box<By>),
+    /// Matches a node if either of the given queries match it.
+    Or(Box<By>, Box<By>),
+    /// Matches a node if the given query does not match it.
+    Not(Box<By>),
 }
 
 /// Ways we can interact with nodes.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Interaction {
     /// Click on the node.
     Click,
+    /// Move input focus to the node.
+    SetFocus,
+    /// Enter the given text into the node. Where the node has an editable-text interface,
+    /// its whole contents are replaced by the text; otherwise the node is focused and the
+    /// text is typed as synthesised key events.
+    TypeText(String),
+    /// Invoke a named action exposed by the node, e.g. `"activate"` or `"expand or contract"`.
+    InvokeAction(String),
 }
@@ -1,12 +1,22 @@
+use std::time::{Duration, Instant};
+
 use atspi::{
+    events::{
+        object::{ChildrenChangedEvent, PropertyChangeEvent, StateChangedEvent, TextChangedEvent},
+        GenericEvent, ObjectEvents,
+    },
     proxy::{
         accessible::{AccessibleProxy, ObjectRefExt},
         action::ActionProxy,
+        component::ComponentProxy,
+        device_event_controller::{DeviceEventControllerProxy, KeySynthType},
+        editable_text::EditableTextProxy,
+        text::TextProxy,
     },
     zbus::{names::BusName, proxy::CacheProperties},
-    AccessibilityConnection, Interface, Role,
+    AccessibilityConnection, Event, Interface, InterfaceSet, Role,
 };
-use futures::future::try_join_all;
+use futures::{future::try_join_all, FutureExt, StreamExt};
 
 use crate::prelude::*;
 
@@ -20,7 +30,8 @@ struct TreeNode {
 
     accessible_id: Option<String>,
     name: Option<String>,
-    _role: Role,
+    role: Role,
+    state: atspi::State,
     children: Vec<TreeNode>,
 }
 
@@ -31,6 +42,7 @@ struct NodeDetails {
     accessible_id: Option<String>,
     name: Option<String>,
     role: Role,
+    state: atspi::State,
 }
 
 impl TreeNode {
@@ -77,7 +89,8 @@ impl TreeNode {
                     path: details.path,
                     accessible_id: details.accessible_id,
                     name: details.name,
-                    _role: details.role,
+                    role: details.role,
+                    state: details.state,
                     children: Vec::new(),
                 })
                 .collect::<Vec<_>>();
@@ -88,13 +101,15 @@ impl TreeNode {
                 accessible_id,
                 name,
                 role,
+                state,
             } = Self::get_node_details(&ap).await?;
             nodes.push(TreeNode {
                 destination,
                 path,
                 accessible_id,
                 name,
-                _role: role,
+                role,
+                state,
                 children,
             });
         }
@@ -126,36 +141,145 @@ impl TreeNode {
             accessible_id: node.accessible_id().await.ok(),
             name: node.name().await.ok(),
             role: node.get_role().await?,
+            state: node.get_state().await?,
         })
     }
 
-    fn bfs(&self, by: By) -> Option<TreeNode> {
-        // Check match
-        match &by {
-            By::Tag(tag) => {
-                if self
-                    .accessible_id
-                    .as_ref()
-                    .map(|t| t == tag)
-                    .unwrap_or(false)
-                {
-                    return Some(self.clone());
-                }
-            }
-            By::Text(text) => {
-                if self.name.as_ref().map(|t| t == text).unwrap_or(false) {
-                    return Some(self.clone());
-                }
+    /// Whether this node, on its own, satisfies `by`.
+    fn matches(&self, by: &CompiledBy) -> bool {
+        match by {
+            CompiledBy::Tag(tag) => {
+                self.accessible_id.as_deref().map(|t| t == *tag).unwrap_or(false)
             }
+            CompiledBy::Text(text) => self
+                .name
+                .as_deref()
+                .map(|n| n.contains(text))
+                .unwrap_or(false),
+            CompiledBy::TextContains {
+                needle,
+                case_insensitive,
+            } => self
+                .name
+                .as_ref()
+                .map(|n| {
+                    if *case_insensitive {
+                        n.to_lowercase().contains(&needle.to_lowercase())
+                    } else {
+                        n.contains(needle.as_str())
+                    }
+                })
+                .unwrap_or(false),
+            CompiledBy::TextRegex(re) => re
+                .as_ref()
+                .and_then(|re| self.name.as_ref().map(|n| re.is_match(n)))
+                .unwrap_or(false),
+            CompiledBy::Role(role) => self.role == *role,
+            CompiledBy::State(state) => self.state.contains(*state),
+            CompiledBy::And(a, b) => self.matches(a) && self.matches(b),
+            CompiledBy::Or(a, b) => self.matches(a) || self.matches(b),
+            CompiledBy::Not(inner) => !self.matches(inner),
+        }
+    }
+
+    fn bfs(&self, by: &CompiledBy) -> Option<TreeNode> {
+        if self.matches(by) {
+            return Some(self.clone());
         }
         // Check children
         for child in &self.children {
-            if let Some(node) = child.bfs(by.clone()) {
+            if let Some(node) = child.bfs(by) {
                 return Some(node);
             }
         }
         None
     }
+
+    /// Collect every node in the tree that satisfies `by`.
+    fn bfs_all(&self, by: &CompiledBy, results: &mut Vec<TreeNode>) {
+        if self.matches(by) {
+            results.push(self.clone());
+        }
+        for child in &self.children {
+            child.bfs_all(by, results);
+        }
+    }
+
+    /// Find the cached node at `(destination, path)`, so it can be replaced in place
+    /// without rebuilding the whole tree.
+    fn find_mut(&mut self, destination: &BusName<'static>, path: &str) -> Option<&mut TreeNode> {
+        if &self.destination == destination && self.path == path {
+            return Some(self);
+        }
+        for child in &mut self.children {
+            if let Some(found) = child.find_mut(destination, path) {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    /// Remove the cached node at `(destination, path)` from the tree, if present.
+    ///
+    /// Returns `true` if a node was removed.
+    fn remove(&mut self, destination: &BusName<'static>, path: &str) -> bool {
+        let before = self.children.len();
+        self.children
+            .retain(|child| !(&child.destination == destination && child.path == path));
+        if self.children.len() != before {
+            return true;
+        }
+        self.children
+            .iter_mut()
+            .any(|child| child.remove(destination, path))
+    }
+}
+
+/// A [`By`] query with its [`By::TextRegex`] patterns compiled up front, so a single
+/// query only pays the regex-compilation cost once rather than once per node visited
+/// while walking the cached tree.
+enum CompiledBy<'b> {
+    Tag(&'b str),
+    Text(&'b str),
+    TextContains {
+        needle: &'b str,
+        case_insensitive: bool,
+    },
+    /// `None` when the source pattern failed to compile; such a query matches nothing.
+    TextRegex(Option<regex::Regex>),
+    Role(atspi::Role),
+    State(atspi::State),
+    And(Box<CompiledBy<'b>>, Box<CompiledBy<'b>>),
+    Or(Box<CompiledBy<'b>>, Box<CompiledBy<'b>>),
+    Not(Box<CompiledBy<'b>>),
+}
+
+impl<'b> CompiledBy<'b> {
+    fn compile(by: &'b By) -> Self {
+        match by {
+            By::Tag(tag) => CompiledBy::Tag(tag),
+            By::Text(text) => CompiledBy::Text(text),
+            By::TextContains {
+                needle,
+                case_insensitive,
+            } => CompiledBy::TextContains {
+                needle,
+                case_insensitive: *case_insensitive,
+            },
+            By::TextRegex(pattern) => match regex::Regex::new(pattern) {
+                Ok(re) => CompiledBy::TextRegex(Some(re)),
+                Err(e) => {
+                    log::warn!("By::TextRegex({pattern:?}) is not a valid regex: {e}");
+                    CompiledBy::TextRegex(None)
+                }
+            },
+            By::Role(role) => CompiledBy::Role(*role),
+            By::State(state) => CompiledBy::State(*state),
+            By::And(a, b) => CompiledBy::And(Box::new(Self::compile(a)), Box::new(Self::compile(b))),
+            By::Or(a, b) => CompiledBy::Or(Box::new(Self::compile(a)), Box::new(Self::compile(b))),
+            By::Not(inner) => CompiledBy::Not(Box::new(Self::compile(inner))),
+        }
+    }
 }
 
 /// An error from the ATSPI test interface.
@@ -186,6 +310,10 @@ pub enum TestByATSPIError {
 pub struct TestByATSPI<'p> {
     atspi: AccessibilityConnection,
     root_proxy: AccessibleProxy<'p>,
+    /// The accessibility tree, built once on connect and kept up to date incrementally
+    /// from `ChildrenChanged`/`PropertyChange`/`StateChanged` events rather than being
+    /// rebuilt from scratch on every `find`.
+    tree: TreeNode,
 }
 impl<'p> TestByATSPI<'p> {
     async fn connect_impl(
@@ -237,7 +365,22 @@ impl<'p> TestByATSPI<'p> {
             .build()
             .await?;
         log::debug!("Root: {root_proxy:?}");
-        Ok(TestByATSPI { atspi, root_proxy })
+
+        // Enable delivery of the events that drive `wait_for` and keep the cached tree
+        // up to date, so both can react to changes instead of polling.
+        atspi.register_event::<StateChangedEvent>().await?;
+        atspi.register_event::<TextChangedEvent>().await?;
+        atspi.register_event::<ChildrenChangedEvent>().await?;
+        atspi.register_event::<PropertyChangeEvent>().await?;
+
+        log::trace!("Building initial tree");
+        let tree = TreeNode::from_accessible_proxy(root_proxy.clone()).await?;
+
+        Ok(TestByATSPI {
+            atspi,
+            root_proxy,
+            tree,
+        })
     }
 
     async fn find_impl(
@@ -246,10 +389,11 @@ impl<'p> TestByATSPI<'p> {
     ) -> Result<Option<<TestByATSPI<'p> as TestByA11y>::Node>, <TestByATSPI<'p> as TestByA11y>::Error>
     {
         log::trace!("Searching for {by:?}");
-        // Build tree
-        let tree = self.build_tree().await?;
-        // Search tree
-        let node = tree.bfs(by);
+        // Bring the cached tree up to date with anything that has changed, then search it
+        // directly instead of rebuilding it from scratch.
+        self.apply_pending_events().await?;
+        let compiled = CompiledBy::compile(&by);
+        let node = self.tree.bfs(&compiled);
         if let Some(node) = node {
             log::trace!("Found node, building new proxy");
             return Ok(Some(
@@ -265,6 +409,78 @@ impl<'p> TestByATSPI<'p> {
         Ok(None)
     }
 
+    async fn find_all_impl(
+        &mut self,
+        by: By,
+    ) -> Result<Vec<<TestByATSPI<'p> as TestByA11y>::Node>, <TestByATSPI<'p> as TestByA11y>::Error>
+    {
+        log::trace!("Searching for all matches of {by:?}");
+        self.apply_pending_events().await?;
+        let compiled = CompiledBy::compile(&by);
+        let mut matches = Vec::new();
+        self.tree.bfs_all(&compiled, &mut matches);
+
+        let connection = self.atspi.connection().clone();
+        Ok(try_join_all(matches.into_iter().map(|node| {
+            let connection = connection.clone();
+            async move {
+                AccessibleProxy::builder(&connection)
+                    .destination(node.destination.clone())?
+                    .path(node.path.clone())?
+                    .interface(PROXY_INTERFACE)?
+                    .cache_properties(CacheProperties::No)
+                    .build()
+                    .await
+            }
+        }))
+        .await?)
+    }
+
+    async fn wait_for_impl(
+        &mut self,
+        by: By,
+        timeout: Duration,
+    ) -> Result<Option<<TestByATSPI<'p> as TestByA11y>::Node>, <TestByATSPI<'p> as TestByA11y>::Error>
+    {
+        log::trace!("Waiting for {by:?} (timeout: {timeout:?})");
+        if let Some(node) = self.find_impl(by.clone()).await? {
+            return Ok(Some(node));
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut events = self.atspi.event_stream();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                log::trace!("Timed out waiting for {by:?}");
+                return Ok(None);
+            }
+
+            futures::select! {
+                _ = futures_timer::Delay::new(remaining).fuse() => {
+                    log::trace!("Timed out waiting for {by:?}");
+                    return Ok(None);
+                }
+                event = events.next().fuse() => {
+                    let Some(event) = event else {
+                        // The event stream ended; nothing more will arrive.
+                        return Ok(None);
+                    };
+                    // Fold the event that woke us straight into the cache: `find_impl`
+                    // draws on a freshly constructed event stream of its own, which only
+                    // sees events arriving after it's built, so the one we just received
+                    // here would otherwise never reach the cache.
+                    if let Ok(event) = event {
+                        self.apply_event(event).await?;
+                    }
+                    if let Some(node) = self.find_impl(by.clone()).await? {
+                        return Ok(Some(node));
+                    }
+                }
+            }
+        }
+    }
+
     async fn interact_impl(
         &mut self,
         node: &<TestByATSPI<'p> as TestByA11y>::Node,
@@ -273,43 +489,227 @@ impl<'p> TestByATSPI<'p> {
         log::debug!("Interaction {interaction:?} on {}", node.name().await?);
         let interfaces = node.get_interfaces().await.unwrap_or_default();
         match interaction {
-            Interaction::Click => {
-                // TODO Trigger click
-                if !interfaces.contains(Interface::Action) {
-                    return Err(TestByATSPIError::CannotPerformInteractionOnTarget);
+            Interaction::Click => self.invoke_action_impl(node, &interfaces, "click").await,
+            Interaction::InvokeAction(name) => {
+                self.invoke_action_impl(node, &interfaces, &name).await
+            }
+            Interaction::SetFocus => self.grab_focus_impl(node, &interfaces).await,
+            Interaction::TypeText(text) => {
+                if interfaces.contains(Interface::EditableText) {
+                    let editable_text_proxy = EditableTextProxy::builder(self.atspi.connection())
+                        .destination(node.inner().destination().clone())?
+                        .path(node.inner().path().clone())?
+                        .interface("org.a11y.atspi.EditableText")?
+                        .cache_properties(CacheProperties::No)
+                        .build()
+                        .await?;
+                    editable_text_proxy.set_text_contents(&text).await?;
+                    return Ok(());
                 }
-                let action_proxy = ActionProxy::builder(self.atspi.connection())
-                    .destination(node.inner().destination().clone())?
-                    .path(node.inner().path().clone())?
-                    .interface("org.a11y.atspi.Action")?
-                    .cache_properties(CacheProperties::No)
-                    .build()
-                    .await?;
-                let actions = action_proxy.get_actions().await?;
-                log::trace!("Actions: {actions:?}");
-                for (idx, action) in actions.iter().enumerate() {
-                    if action.name.to_lowercase().contains("click")
-                        || action.description.to_lowercase().contains("click")
-                    {
-                        action_proxy.do_action(idx as i32).await?;
-                        return Ok(());
-                    }
+
+                // No EditableText interface: synthesized key events go to whatever
+                // currently has keyboard focus, so make sure that's `node` first.
+                self.grab_focus_impl(node, &interfaces).await?;
+
+                // Fall back to synthesising key events through the registry's device
+                // event controller, one character at a time.
+                let device_event_controller =
+                    DeviceEventControllerProxy::builder(self.atspi.connection())
+                        .destination(PROXY_DESTINATION)?
+                        .path("/org/a11y/atspi/registry/deviceeventcontroller")?
+                        .interface("org.a11y.atspi.DeviceEventController")?
+                        .cache_properties(CacheProperties::No)
+                        .build()
+                        .await?;
+                for ch in text.chars() {
+                    device_event_controller
+                        .generate_keyboard_event(0, &ch.to_string(), KeySynthType::String)
+                        .await?;
                 }
-                Err(TestByATSPIError::CannotFindAction)
+                Ok(())
             }
         }
     }
 
+    async fn invoke_action_impl(
+        &self,
+        node: &<TestByATSPI<'p> as TestByA11y>::Node,
+        interfaces: &InterfaceSet,
+        needle: &str,
+    ) -> Result<(), <TestByATSPI<'p> as TestByA11y>::Error> {
+        if !interfaces.contains(Interface::Action) {
+            return Err(TestByATSPIError::CannotPerformInteractionOnTarget);
+        }
+        let action_proxy = ActionProxy::builder(self.atspi.connection())
+            .destination(node.inner().destination().clone())?
+            .path(node.inner().path().clone())?
+            .interface("org.a11y.atspi.Action")?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let actions = action_proxy.get_actions().await?;
+        log::trace!("Actions: {actions:?}");
+        let needle = needle.to_lowercase();
+        for (idx, action) in actions.iter().enumerate() {
+            if action.name.to_lowercase().contains(&needle)
+                || action.description.to_lowercase().contains(&needle)
+            {
+                action_proxy.do_action(idx as i32).await?;
+                return Ok(());
+            }
+        }
+        Err(TestByATSPIError::CannotFindAction)
+    }
+
+    async fn grab_focus_impl(
+        &self,
+        node: &<TestByATSPI<'p> as TestByA11y>::Node,
+        interfaces: &InterfaceSet,
+    ) -> Result<(), <TestByATSPI<'p> as TestByA11y>::Error> {
+        if !interfaces.contains(Interface::Component) {
+            return Err(TestByATSPIError::CannotPerformInteractionOnTarget);
+        }
+        let component_proxy = ComponentProxy::builder(self.atspi.connection())
+            .destination(node.inner().destination().clone())?
+            .path(node.inner().path().clone())?
+            .interface("org.a11y.atspi.Component")?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        component_proxy.grab_focus().await?;
+        Ok(())
+    }
+
     async fn get_text_impl(
         &mut self,
         node: &<TestByATSPI<'p> as TestByA11y>::Node,
     ) -> Result<String, <TestByATSPI<'p> as TestByA11y>::Error> {
-        Ok(node.name().await?)
+        let interfaces = node.get_interfaces().await.unwrap_or_default();
+        if !interfaces.contains(Interface::Text) {
+            return Ok(node.name().await?);
+        }
+        let text_proxy = self.text_proxy_for(node).await?;
+        let character_count = text_proxy.character_count().await?;
+        Ok(text_proxy.get_text(0, character_count).await?)
+    }
+
+    async fn get_caret_offset_impl(
+        &mut self,
+        node: &<TestByATSPI<'p> as TestByA11y>::Node,
+    ) -> Result<usize, <TestByATSPI<'p> as TestByA11y>::Error> {
+        let interfaces = node.get_interfaces().await.unwrap_or_default();
+        if !interfaces.contains(Interface::Text) {
+            return Ok(0);
+        }
+        let text_proxy = self.text_proxy_for(node).await?;
+        Ok(text_proxy.caret_offset().await?.max(0) as usize)
+    }
+
+    async fn get_selection_impl(
+        &mut self,
+        node: &<TestByATSPI<'p> as TestByA11y>::Node,
+    ) -> Result<Option<(usize, usize)>, <TestByATSPI<'p> as TestByA11y>::Error> {
+        let interfaces = node.get_interfaces().await.unwrap_or_default();
+        if !interfaces.contains(Interface::Text) {
+            return Ok(None);
+        }
+        let text_proxy = self.text_proxy_for(node).await?;
+        if text_proxy.n_selections().await? <= 0 {
+            return Ok(None);
+        }
+        let (start, end) = text_proxy.get_selection(0).await?;
+        let (start, end) = (start.max(0) as usize, end.max(0) as usize);
+        if start == end {
+            return Ok(None);
+        }
+        Ok(Some((start, end)))
+    }
+
+    async fn text_proxy_for(
+        &self,
+        node: &<TestByATSPI<'p> as TestByA11y>::Node,
+    ) -> Result<TextProxy<'p>, <TestByATSPI<'p> as TestByA11y>::Error> {
+        Ok(TextProxy::builder(self.atspi.connection())
+            .destination(node.inner().destination().clone())?
+            .path(node.inner().path().clone())?
+            .interface("org.a11y.atspi.Text")?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
     }
 
-    async fn build_tree(&self) -> Result<TreeNode, atspi::AtspiError> {
-        log::trace!("Building tree");
-        TreeNode::from_accessible_proxy(self.root_proxy.clone()).await
+    /// Drain any accessibility events that have already arrived and fold them into the
+    /// cached tree, without blocking to wait for more.
+    async fn apply_pending_events(&mut self) -> Result<(), <TestByATSPI<'p> as TestByA11y>::Error> {
+        let mut events = self.atspi.event_stream();
+        while let std::task::Poll::Ready(Some(event)) = futures::poll!(events.next()) {
+            if let Ok(event) = event {
+                self.apply_event(event).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fold a single accessibility event into the cached tree.
+    async fn apply_event(
+        &mut self,
+        event: Event,
+    ) -> Result<(), <TestByATSPI<'p> as TestByA11y>::Error> {
+        let (destination, path) = match &event {
+            Event::Object(ObjectEvents::StateChanged(e)) => {
+                (e.sender().to_owned().into(), e.path().as_str().to_string())
+            }
+            Event::Object(ObjectEvents::TextChanged(e)) => {
+                (e.sender().to_owned().into(), e.path().as_str().to_string())
+            }
+            Event::Object(ObjectEvents::ChildrenChanged(e)) => {
+                (e.sender().to_owned().into(), e.path().as_str().to_string())
+            }
+            Event::Object(ObjectEvents::PropertyChange(e)) => {
+                (e.sender().to_owned().into(), e.path().as_str().to_string())
+            }
+            // Only the object events above affect the cached tree.
+            _ => return Ok(()),
+        };
+
+        // Events aren't filtered by sender at subscription time, so most events we see
+        // belong to other applications entirely. Discard those before doing any D-Bus
+        // round trip or tree walk for them.
+        if destination != self.tree.destination {
+            return Ok(());
+        }
+
+        self.refresh_subtree(destination, path).await
+    }
+
+    /// Re-fetch the subtree rooted at `(destination, path)` and splice it into the cache
+    /// in place, rather than rebuilding the whole application tree.
+    async fn refresh_subtree(
+        &mut self,
+        destination: BusName<'static>,
+        path: String,
+    ) -> Result<(), <TestByATSPI<'p> as TestByA11y>::Error> {
+        log::trace!("Refreshing cached subtree at {destination}{path}");
+        let proxy = AccessibleProxy::builder(self.atspi.connection())
+            .destination(destination.clone())?
+            .path(path.clone())?
+            .interface(PROXY_INTERFACE)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let Ok(refreshed) = TreeNode::from_accessible_proxy(proxy).await else {
+            // The node has been removed entirely; prune it from the cache rather than
+            // leaving a stale subtree behind.
+            self.tree.remove(&destination, &path);
+            return Ok(());
+        };
+
+        if self.tree.destination == destination && self.tree.path == path {
+            self.tree = refreshed;
+        } else if let Some(existing) = self.tree.find_mut(&destination, &path) {
+            *existing = refreshed;
+        }
+        Ok(())
     }
 }
 
@@ -330,6 +730,13 @@ impl<'p> TestByA11y for TestByATSPI<'p> {
         r
     }
 
+    fn find_all(&mut self, by: By) -> Result<Vec<Self::Node>, Self::Error> {
+        log::trace!("find_all(by: {by:?})");
+        let r = futures::executor::block_on(self.find_all_impl(by.clone()));
+        log::trace!("find_all(by: {by:?}) = {r:?}");
+        r
+    }
+
     fn interact(&mut self, node: &Self::Node, interaction: Interaction) -> Result<(), Self::Error> {
         log::trace!("interact(node: {node:?}, interaction: {interaction:?})");
         let r = futures::executor::block_on(self.interact_impl(node, interaction));
@@ -343,4 +750,167 @@ impl<'p> TestByA11y for TestByATSPI<'p> {
         log::trace!("get_text(node: ...) = {r:?}");
         r
     }
+
+    fn get_caret_offset(&mut self, node: &Self::Node) -> Result<usize, Self::Error> {
+        log::trace!("get_caret_offset(node: {node:?})");
+        let r = futures::executor::block_on(self.get_caret_offset_impl(node));
+        log::trace!("get_caret_offset(node: ...) = {r:?}");
+        r
+    }
+
+    fn get_selection(&mut self, node: &Self::Node) -> Result<Option<(usize, usize)>, Self::Error> {
+        log::trace!("get_selection(node: {node:?})");
+        let r = futures::executor::block_on(self.get_selection_impl(node));
+        log::trace!("get_selection(node: ...) = {r:?}");
+        r
+    }
+
+    fn wait_for(
+        &mut self,
+        by: By,
+        timeout: std::time::Duration,
+    ) -> Result<Option<Self::Node>, Self::Error> {
+        log::trace!("wait_for(by: {by:?}, timeout: {timeout:?})");
+        let r = futures::executor::block_on(self.wait_for_impl(by.clone(), timeout));
+        log::trace!("wait_for(by: {by:?}, timeout: {timeout:?}) = {r:?}");
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(
+        path: &str,
+        accessible_id: Option<&str>,
+        name: Option<&str>,
+        role: Role,
+        state: atspi::State,
+        children: Vec<TreeNode>,
+    ) -> TreeNode {
+        TreeNode {
+            destination: BusName::try_from("org.test.App").unwrap(),
+            path: path.to_string(),
+            accessible_id: accessible_id.map(str::to_string),
+            name: name.map(str::to_string),
+            role,
+            state,
+            children,
+        }
+    }
+
+    fn sample_tree() -> TreeNode {
+        node(
+            "/root",
+            Some("root"),
+            Some("Calculator"),
+            Role::Application,
+            atspi::State::empty(),
+            vec![
+                node(
+                    "/root/0",
+                    Some("btn9"),
+                    Some("9"),
+                    Role::PushButton,
+                    atspi::State::Enabled | atspi::State::Focusable,
+                    Vec::new(),
+                ),
+                node(
+                    "/root/1",
+                    Some("btn-equals"),
+                    Some("="),
+                    Role::PushButton,
+                    atspi::State::Enabled | atspi::State::Focused,
+                    Vec::new(),
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn text_matches_partially_and_case_sensitively() {
+        let tree = sample_tree();
+        assert!(tree.matches(&CompiledBy::compile(&By::Text("Calc".to_string()))));
+        assert!(!tree.matches(&CompiledBy::compile(&By::Text("calc".to_string()))));
+    }
+
+    #[test]
+    fn text_contains_can_ignore_case() {
+        let tree = sample_tree();
+        let by = By::TextContains {
+            needle: "calc".to_string(),
+            case_insensitive: true,
+        };
+        assert!(tree.matches(&CompiledBy::compile(&by)));
+    }
+
+    #[test]
+    fn text_regex_matches_by_pattern() {
+        let tree = sample_tree();
+        let compiled = CompiledBy::compile(&By::TextRegex("^=$".to_string()));
+        let found = tree.bfs(&compiled).expect("should find the equals button");
+        assert_eq!(found.name.as_deref(), Some("="));
+    }
+
+    #[test]
+    fn invalid_regex_matches_nothing() {
+        let tree = sample_tree();
+        let compiled = CompiledBy::compile(&By::TextRegex("[unclosed".to_string()));
+        assert!(tree.bfs(&compiled).is_none());
+    }
+
+    #[test]
+    fn and_or_not_combinators_compose() {
+        let tree = sample_tree();
+        let by = By::And(
+            Box::new(By::Role(Role::PushButton)),
+            Box::new(By::Text("=".to_string())),
+        );
+        let found = tree.bfs(&CompiledBy::compile(&by)).unwrap();
+        assert_eq!(found.accessible_id.as_deref(), Some("btn-equals"));
+
+        let by_or = By::Or(
+            Box::new(By::Tag("missing".to_string())),
+            Box::new(By::Tag("btn-equals".to_string())),
+        );
+        let found = tree.bfs(&CompiledBy::compile(&by_or)).unwrap();
+        assert_eq!(found.accessible_id.as_deref(), Some("btn-equals"));
+
+        let by_not = By::Not(Box::new(By::Role(Role::PushButton)));
+        assert!(tree.matches(&CompiledBy::compile(&by_not)));
+    }
+
+    #[test]
+    fn bfs_all_collects_every_match() {
+        let tree = sample_tree();
+        let compiled = CompiledBy::compile(&By::Role(Role::PushButton));
+        let mut results = Vec::new();
+        tree.bfs_all(&compiled, &mut results);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn find_mut_locates_nested_node_by_destination_and_path() {
+        let mut tree = sample_tree();
+        let destination = tree.destination.clone();
+        let found = tree.find_mut(&destination, "/root/1").unwrap();
+        assert_eq!(found.accessible_id.as_deref(), Some("btn-equals"));
+    }
+
+    #[test]
+    fn remove_prunes_nested_node_and_reports_success() {
+        let mut tree = sample_tree();
+        let destination = tree.destination.clone();
+        assert!(tree.remove(&destination, "/root/0"));
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].path, "/root/1");
+    }
+
+    #[test]
+    fn remove_reports_failure_for_unknown_node() {
+        let mut tree = sample_tree();
+        let destination = tree.destination.clone();
+        assert!(!tree.remove(&destination, "/root/missing"));
+    }
 }
@@ -72,10 +72,10 @@ fn test_ui_1() {
         let btn_equals = test.find(By::Text("=".to_string())).unwrap().unwrap();
         test.interact(&btn_equals, Interaction::Click).unwrap();
 
-        sleep(Duration::from_millis(100));
-
         // Check that we find the result "10" written somewhere
-        let result = test.find(By::Text("10".to_string())).unwrap();
+        let result = test
+            .wait_for(By::Text("10".to_string()), Duration::from_secs(2))
+            .unwrap();
         assert!(result.is_some());
     });
 }
@@ -98,10 +98,10 @@ fn test_ui_2() {
         let btn_equals = test.find(By::Text("=".to_string())).unwrap().unwrap();
         test.interact(&btn_equals, Interaction::Click).unwrap();
 
-        sleep(Duration::from_millis(100));
-
         // Check that we find the result "10" written somewhere
-        let result = test.find(By::Text("18".to_string())).unwrap();
+        let result = test
+            .wait_for(By::Text("18".to_string()), Duration::from_secs(2))
+            .unwrap();
         assert!(result.is_some());
     });
 }